@@ -0,0 +1,14 @@
+//! Undo/redo journal entries.
+//!
+//! Each entry records enough to invert one (possibly coalesced) edit:
+//! the cursor position before the edit happened, and for deletions, the
+//! char that was removed so it can be typed back in.
+
+#[derive(Debug, Clone)]
+pub enum Journal {
+    /// One or more consecutive `Insert`s, coalesced into a single entry.
+    Insert { pos: usize, text: String },
+    DeleteLeft { cursor_before: usize, removed: char },
+    DeleteRight { cursor_before: usize, removed: char },
+    Newline { pos: usize },
+}
@@ -0,0 +1,66 @@
+//! Lazy, line-indexed file loading.
+//!
+//! Reading a large file with `read_to_string` blocks until the whole file
+//! is in memory. `Loader` instead wraps the file in a `BufReader` and reads
+//! it one line at a time, on demand, so opening a multi-megabyte file is
+//! instant and the document only grows as the viewport scrolls into
+//! unloaded territory.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
+pub struct Loader {
+    reader: BufReader<File>,
+    pub eof_reached: bool,
+    /// How many lines have been read in so far. Cursor/line math still
+    /// works off `Poem::text()` (the buffer is the source of truth once
+    /// edits happen), so this only needs to be a count, not a per-line
+    /// index.
+    lines_loaded: usize,
+}
+
+impl Loader {
+    pub fn open(path: &str) -> io::Result<Self> {
+        let file = File::open(path)?;
+        Ok(Loader {
+            reader: BufReader::new(file),
+            eof_reached: false,
+            lines_loaded: 0,
+        })
+    }
+
+    /// Reads the next line (including its trailing newline, if any).
+    /// Returns an empty string once EOF is reached.
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        let bytes_read = self.reader.read_line(&mut line)?;
+        if bytes_read == 0 {
+            self.eof_reached = true;
+            return Ok(String::new());
+        }
+
+        self.lines_loaded += 1;
+        Ok(line)
+    }
+
+    /// Reads more lines until `target_line` (0-indexed) has been loaded or
+    /// EOF is hit, returning whatever new text was read in along the way.
+    pub fn ensure_loaded_through(&mut self, target_line: usize) -> io::Result<String> {
+        let mut appended = String::new();
+        while !self.eof_reached && self.lines_loaded <= target_line {
+            appended.push_str(&self.read_line()?);
+        }
+        Ok(appended)
+    }
+
+    /// Reads in whatever of the file remains unread, returning the text
+    /// that was appended. Used before destructive operations (saving,
+    /// jumping to the tail view) that need the complete document.
+    pub fn load_remaining(&mut self) -> io::Result<String> {
+        let mut appended = String::new();
+        while !self.eof_reached {
+            appended.push_str(&self.read_line()?);
+        }
+        Ok(appended)
+    }
+}
@@ -1,17 +1,57 @@
 extern crate termion;
 
-use std::io::{stdout, Read, Stdout, Write};
+mod journal;
+mod loader;
+mod piece_table;
+mod tail;
+
+use std::io::{stdout, Stdout, Write};
+use journal::Journal;
+use loader::Loader;
+use piece_table::PieceTable;
 use termion::raw::RawTerminal;
+use termion::screen::AlternateScreen;
 use termion::{event::Key, input::TermRead, raw::IntoRawMode, terminal_size};
 
+/// The whole editing session runs on the alternate screen so the user's
+/// previous shell contents are preserved and restored when `poed` quits —
+/// `AlternateScreen` and `RawTerminal` both restore the terminal in their
+/// own `Drop` impls, so this happens whether we exit via Esc, a panic, or
+/// an I/O error, with no manual teardown required.
+type Screen = AlternateScreen<RawTerminal<Stdout>>;
+
 const X_PADDING: usize = 3;
 const Y_PADDING: usize = 1;
+/// Number of lines shown by the jump-to-end tail view.
+const TAIL_LINES: usize = 200;
+
+/// How many lines of the buffer fit on screen at once, leaving room for the
+/// name line and the box border.
+fn viewport_height(size: (u16, u16)) -> usize {
+    (size.1 as usize).saturating_sub(2 * Y_PADDING + 3).max(1)
+}
 
 struct Poem {
-    buffer: String,
+    buffer: PieceTable,
     cursor: usize,
     target_line_pos: usize,
     name: Option<String>,
+    undo: Vec<Journal>,
+    redo: Vec<Journal>,
+    coalescing: bool,
+    /// Index of the first visible line, for documents too tall to fit on
+    /// screen at once.
+    scroll: usize,
+    /// Set on every edit (and undo/redo) and cleared on save. Used to keep
+    /// destructive buffer swaps (e.g. jumping to the tail view) from
+    /// silently discarding in-progress work.
+    dirty: bool,
+    /// Set once the buffer has been replaced by a tail view that only
+    /// holds the last few lines of the file, read straight off disk
+    /// without going through `loader`. Distinct from the loader's own
+    /// `eof_reached`: the file's middle section was never actually read,
+    /// so saving must not treat the buffer as the whole document.
+    truncated_view: bool,
 }
 
 fn make_input(input: &String) -> (Vec<String>, usize, usize) {
@@ -89,13 +129,23 @@ enum EditOperation {
 impl Poem {
     fn from_str(text: &str) -> Self {
         Poem {
-            buffer: String::from(text),
+            buffer: PieceTable::new(String::from(text)),
             cursor: 0,
             target_line_pos: 0,
             name: None,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            coalescing: false,
+            scroll: 0,
+            dirty: false,
+            truncated_view: false,
         }
     }
 
+    fn text(&self) -> String {
+        self.buffer.text()
+    }
+
     fn with_name(self, name: String) -> Self {
         Poem {
             name: Some(name),
@@ -107,65 +157,176 @@ impl Poem {
         use EditOperation::*;
         match edit {
             Insert(c) => {
-                self.buffer.insert(self.cursor, c);
-                self.cursor += 1;
+                let pos = self.cursor;
+                self.buffer.insert_char(pos, c);
+                self.cursor += c.len_utf8();
+                self.push_insert(pos, c);
             }
             DeleteRight => {
                 if self.cursor == self.buffer.len() {
                     return;
                 }
-                self.buffer.remove(self.cursor);
+                let cursor_before = self.cursor;
+                let removed = self.buffer.delete_char(self.cursor);
+                self.push_journal(Journal::DeleteRight {
+                    cursor_before,
+                    removed,
+                });
             }
             DeleteLeft => {
                 if self.cursor == 0 {
                     return;
                 }
+                let cursor_before = self.cursor;
                 self.cursor -= 1;
-                if self.cursor + 1 == self.buffer.len() {
-                    // String.remove doesn't work if we want
-                    // to truncate the very last character, so
-                    // we have to use a different method
-
-                    self.buffer.pop();
-                } else {
-                    self.buffer.remove(self.cursor);
-                }
+                let removed = self.buffer.delete_char(self.cursor);
+                self.push_journal(Journal::DeleteLeft {
+                    cursor_before,
+                    removed,
+                });
             }
             Newline => {
-                self.buffer.insert(self.cursor, '\n');
+                let pos = self.cursor;
+                self.buffer.insert_char(pos, '\n');
+                self.push_journal(Journal::Newline { pos });
             }
         }
     }
 
-    fn get_cursor_offset(&self) -> (u16, u16) {
-        let mut counter = self.cursor;
-        let mut res: (u16, u16) = (0, 0);
+    /// Records an `Insert`, coalescing it onto the previous journal entry
+    /// if the caller hasn't moved the cursor, deleted, or inserted a
+    /// newline since.
+    fn push_insert(&mut self, pos: usize, c: char) {
+        self.redo.clear();
+        self.dirty = true;
+        if self.coalescing {
+            if let Some(Journal::Insert { text, .. }) = self.undo.last_mut() {
+                text.push(c);
+                return;
+            }
+        }
+        self.undo.push(Journal::Insert {
+            pos,
+            text: c.to_string(),
+        });
+        self.coalescing = true;
+    }
+
+    fn push_journal(&mut self, entry: Journal) {
+        self.redo.clear();
+        self.coalescing = false;
+        self.dirty = true;
+        self.undo.push(entry);
+    }
 
-        for (idx, length) in self.buffer.split('\n').map(|e| e.len() + 1).enumerate() {
-            if counter >= length {
-                counter -= length;
-                continue;
+    /// Stops the next `Insert` from being coalesced onto the last one.
+    /// Called whenever the cursor moves on its own (arrow keys, Home/End).
+    fn break_coalescing(&mut self) {
+        self.coalescing = false;
+    }
+
+    fn undo(&mut self) {
+        let Some(entry) = self.undo.pop() else {
+            return;
+        };
+        self.coalescing = false;
+        self.dirty = true;
+        match &entry {
+            Journal::Insert { pos, text } => {
+                self.buffer.delete_n(*pos, text.chars().count());
+                self.cursor = *pos;
+            }
+            Journal::DeleteLeft {
+                cursor_before,
+                removed,
+            } => {
+                self.buffer.insert_char(*cursor_before - 1, *removed);
+                self.cursor = *cursor_before;
+            }
+            Journal::DeleteRight {
+                cursor_before,
+                removed,
+            } => {
+                self.buffer.insert_char(*cursor_before, *removed);
+                self.cursor = *cursor_before;
+            }
+            Journal::Newline { pos } => {
+                self.buffer.delete_char(*pos);
+                self.cursor = *pos;
+            }
+        }
+        self.redo.push(entry);
+    }
+
+    fn redo(&mut self) {
+        let Some(entry) = self.redo.pop() else {
+            return;
+        };
+        self.coalescing = false;
+        self.dirty = true;
+        match &entry {
+            Journal::Insert { pos, text } => {
+                self.buffer.insert_str(*pos, text);
+                self.cursor = *pos + text.len();
+            }
+            Journal::DeleteLeft { cursor_before, .. } => {
+                self.buffer.delete_char(*cursor_before - 1);
+                self.cursor = *cursor_before - 1;
+            }
+            Journal::DeleteRight { cursor_before, .. } => {
+                self.buffer.delete_char(*cursor_before);
+                self.cursor = *cursor_before;
+            }
+            Journal::Newline { pos } => {
+                self.buffer.insert_char(*pos, '\n');
+                self.cursor = *pos;
             }
-            res = (counter as u16, idx as u16);
-            break;
         }
-        res
+        self.undo.push(entry);
+    }
+
+    fn get_cursor_offset(&self) -> (u16, u16) {
+        let (col, line) = self.buffer.cursor_offset(self.cursor);
+        (col as u16, line as u16)
     }
 
     fn cursor_end_line(&mut self) {
-        let current_line = self.get_cursor_offset().1;
-        self.cursor += self.buffer.lines().nth(current_line.into()).unwrap().len()
-            - self.get_cursor_offset().0 as usize;
+        let (col, line) = self.buffer.cursor_offset(self.cursor);
+        self.cursor += self.buffer.line_len(line) - col;
     }
 
     fn cursor_start_line(&mut self) {
-        while self.get_cursor_offset().0 != 0 {
-            self.cursor -= 1;
+        let (col, _) = self.buffer.cursor_offset(self.cursor);
+        self.cursor -= col;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coalesced_insert_round_trips_through_undo_redo() {
+        let mut poem = Poem::from_str("");
+        for c in "abc".chars() {
+            poem.modify(EditOperation::Insert(c));
         }
+        assert_eq!(poem.text(), "abc");
+        // Typed without any intervening cursor movement, so the three
+        // inserts above should have coalesced into a single undo entry.
+        assert_eq!(poem.undo.len(), 1);
+
+        poem.undo();
+        assert_eq!(poem.text(), "");
+        assert_eq!(poem.cursor, 0);
+
+        poem.redo();
+        assert_eq!(poem.text(), "abc");
+        assert_eq!(poem.cursor, 3);
     }
 }
 
-fn draw_screen(stdout: &mut RawTerminal<Stdout>, poem: &Poem) {
+fn draw_screen(stdout: &mut Screen, poem: &Poem) {
     write!(
         stdout,
         "{}{}{}",
@@ -176,8 +337,14 @@ fn draw_screen(stdout: &mut RawTerminal<Stdout>, poem: &Poem) {
     .unwrap();
 
     let size = terminal_size().unwrap();
-    let (buf, xs, _) = make_input(&poem.buffer);
-    let (frame, xs, ys) = frame_buffer(&buf, xs, None);
+    let (all_lines, xs, _) = make_input(&poem.text());
+
+    let height = viewport_height(size);
+    let scroll = poem.scroll.min(all_lines.len().saturating_sub(1));
+    let end = (scroll + height).min(all_lines.len());
+    let visible = all_lines[scroll..end].to_vec();
+
+    let (frame, xs, ys) = frame_buffer(&visible, xs, None);
 
     let frame_corner = (size.0 / 2 - (xs / 2) as u16, size.1 / 2 - (ys / 2) as u16);
 
@@ -201,20 +368,13 @@ fn draw_screen(stdout: &mut RawTerminal<Stdout>, poem: &Poem) {
     }
 
     let cursor_offset = poem.get_cursor_offset();
-    /*
-    println!(
-        "cursor at {} -> {:?}; buffer is {} chars long",
-        poem.cursor,
-        poem.get_cursor_offset(),
-        poem.buffer.len(),
-    );
-    */
+    let cursor_line_in_view = (cursor_offset.1 as usize).saturating_sub(scroll) as u16;
     write!(
         stdout,
         "{}",
         termion::cursor::Goto(
             frame_corner.0 + cursor_offset.0 + X_PADDING as u16 + 1,
-            frame_corner.1 + cursor_offset.1 + Y_PADDING as u16 + 1
+            frame_corner.1 + cursor_line_in_view + Y_PADDING as u16 + 1
         )
     );
     stdout.flush().unwrap();
@@ -223,15 +383,22 @@ fn draw_screen(stdout: &mut RawTerminal<Stdout>, poem: &Poem) {
 fn main() {
     let mut args = std::env::args();
 
+    // Loaded lazily below when opening an existing file; stays `None` for a
+    // fresh, empty buffer (the stdin/no-args fallback), since there's
+    // nothing left on disk to stream in.
+    let mut loader: Option<Loader> = None;
+
     let mut poem = match std::env::args().count() {
         1 => Poem::from_str(""),
         2 => {
             let path = args.nth(1).unwrap();
-            let mut file = std::fs::File::open(path.clone()).expect("ERROR: Could not open file.");
-            let mut content = String::new();
-            file.read_to_string(&mut content)
+            let mut l = Loader::open(&path).expect("ERROR: Could not open file.");
+            let size = terminal_size().unwrap_or((80, 24));
+            let initial = l
+                .ensure_loaded_through(viewport_height(size))
                 .expect("ERROR: Could not read file.");
-            Poem::from_str(&content).with_name(path)
+            loader = Some(l);
+            Poem::from_str(&initial).with_name(path)
         }
         _ => {
             eprintln!("USAGE: poed <path/to/file>");
@@ -239,7 +406,7 @@ fn main() {
         }
     };
 
-    let mut stdout = stdout().into_raw_mode().unwrap();
+    let mut stdout: Screen = AlternateScreen::from(stdout().into_raw_mode().unwrap());
 
     let stdin = termion::async_stdin();
     let mut it = stdin.keys();
@@ -255,19 +422,43 @@ fn main() {
                     match key {
                         Key::Esc => break 'run,
                         Key::Ctrl('s') => {
+                            // The tail view only holds the last few lines
+                            // of the file, read straight off disk outside
+                            // of `loader` — saving it as-is would overwrite
+                            // the rest of the file with just that tail.
+                            if poem.truncated_view {
+                                println!(
+                                    "refusing to save: buffer only holds the tail view, reopen the file to save."
+                                );
+                                draw_screen(&mut stdout, &poem);
+                                continue 'run;
+                            }
+
+                            // Saving only what's been scrolled through so
+                            // far would truncate the file on disk, so pull
+                            // in the rest of it before writing.
+                            if let Some(l) = loader.as_mut() {
+                                if !l.eof_reached {
+                                    if let Ok(rest) = l.load_remaining() {
+                                        poem.buffer.extend_original(&rest);
+                                    }
+                                }
+                            }
+
                             let result = {
                                 if let Some(path) = poem.name.as_ref() {
-                                    std::fs::write(path.clone(), poem.buffer.clone())
+                                    std::fs::write(path.clone(), poem.text())
                                 } else {
-                                    poem.name =
-                                        Some(poem.buffer.lines().next().unwrap().to_string());
-                                    poem.buffer =
-                                        poem.buffer.split_once('\n').unwrap().1.to_string();
-                                    std::fs::write(poem.name.as_ref().unwrap(), poem.buffer.clone())
+                                    let text = poem.text();
+                                    let (name, rest) = text.split_once('\n').unwrap();
+                                    poem.name = Some(name.to_string());
+                                    poem.buffer = PieceTable::new(rest.to_string());
+                                    std::fs::write(poem.name.as_ref().unwrap(), poem.text())
                                 }
                             };
 
                             if result.is_ok() {
+                                poem.dirty = false;
                                 println!("saved.")
                             }
                         }
@@ -275,31 +466,74 @@ fn main() {
                         Key::Backspace => poem.modify(EditOperation::DeleteLeft),
                         Key::Delete => poem.modify(EditOperation::DeleteRight),
                         Key::BackTab => poem.modify(EditOperation::Newline),
+                        Key::Ctrl('z') => poem.undo(),
+                        Key::Ctrl('y') => poem.redo(),
+                        // Jump to the end of the document without reading
+                        // the whole file, for files too large to load in
+                        // full (bound to Ctrl+E, read as "End").
+                        Key::Ctrl('e') => {
+                            // This swaps in a tail view read fresh off disk,
+                            // discarding whatever's currently in the buffer,
+                            // so refuse to do it over unsaved edits rather
+                            // than silently losing them.
+                            if poem.dirty {
+                                println!(
+                                    "unsaved changes: save (Ctrl+S) before jumping to the end."
+                                );
+                            } else if let Some(path) = poem.name.clone() {
+                                if let Ok((text, cursor)) =
+                                    tail::read_last_lines(&path, TAIL_LINES)
+                                {
+                                    poem.buffer = PieceTable::new(text);
+                                    poem.cursor = cursor;
+                                    poem.undo.clear();
+                                    poem.redo.clear();
+                                    poem.coalescing = false;
+
+                                    let height =
+                                        viewport_height(terminal_size().unwrap_or((80, 24)));
+                                    poem.scroll = poem
+                                        .text()
+                                        .lines()
+                                        .count()
+                                        .saturating_sub(height);
+
+                                    // This was read straight off disk,
+                                    // bypassing `loader` entirely — the
+                                    // earlier part of the file was never
+                                    // actually read, so `loader.eof_reached`
+                                    // must not be touched here. Mark the
+                                    // buffer itself as a partial view so
+                                    // saving refuses to overwrite the file
+                                    // with just this tail.
+                                    poem.truncated_view = true;
+                                }
+                            }
+                        }
                         Key::Left => {
+                            poem.break_coalescing();
                             if poem.cursor == 0 {
                             } else {
                                 poem.cursor -= 1
                             }
                         }
                         Key::Right => {
+                            poem.break_coalescing();
                             if poem.cursor == poem.buffer.len() {
                             } else {
                                 poem.cursor += 1
                             }
                         }
                         Key::Up => {
+                            poem.break_coalescing();
                             let current_pos = poem.get_cursor_offset();
                             poem.target_line_pos = current_pos.0 as usize;
 
                             if current_pos.1 == 0 {
                                 poem.cursor = 0; // atp equivalent to poem.cursor_start_line();
                             } else {
-                                let prev_line_len = poem
-                                    .buffer
-                                    .lines()
-                                    .nth((current_pos.1 - 1).into())
-                                    .unwrap()
-                                    .len();
+                                let prev_line_len =
+                                    poem.buffer.line_len((current_pos.1 - 1) as usize);
 
                                 if prev_line_len >= poem.target_line_pos {
                                     poem.cursor -= 1;
@@ -312,20 +546,35 @@ fn main() {
                                     poem.cursor -= current_pos.0 as usize + 1;
                                 }
                             }
+
+                            let line = poem.get_cursor_offset().1 as usize;
+                            if line < poem.scroll {
+                                poem.scroll = line;
+                            }
                         }
                         Key::Down => {
+                            poem.break_coalescing();
                             let current_pos = poem.get_cursor_offset();
                             poem.target_line_pos = current_pos.0 as usize;
 
-                            if current_pos.1 == poem.buffer.lines().count() as u16 - 1 {
+                            // Pull in more of the file if we're about to
+                            // scroll past what has been loaded so far. The
+                            // tail view holds only the end of the file, so
+                            // nothing is pulled in while it is shown.
+                            if let Some(l) = loader.as_mut() {
+                                if !l.eof_reached && !poem.truncated_view {
+                                    let more = l
+                                        .ensure_loaded_through(current_pos.1 as usize + 1)
+                                        .unwrap_or_default();
+                                    poem.buffer.extend_original(&more);
+                                }
+                            }
+
+                            if current_pos.1 == poem.buffer.line_count() as u16 - 1 {
                                 poem.cursor_end_line();
                             } else {
-                                let next_line_len = poem
-                                    .buffer
-                                    .lines()
-                                    .nth((current_pos.1 + 1).into())
-                                    .unwrap()
-                                    .len();
+                                let next_line_len =
+                                    poem.buffer.line_len((current_pos.1 + 1) as usize);
 
                                 if next_line_len >= poem.target_line_pos {
                                     poem.cursor += 1;
@@ -343,9 +592,21 @@ fn main() {
                                     poem.cursor_end_line();
                                 }
                             }
+
+                            let line = poem.get_cursor_offset().1 as usize;
+                            let height = viewport_height(terminal_size().unwrap_or((80, 24)));
+                            if line >= poem.scroll + height {
+                                poem.scroll = line + 1 - height;
+                            }
+                        }
+                        Key::End => {
+                            poem.break_coalescing();
+                            poem.cursor_end_line();
+                        }
+                        Key::Home => {
+                            poem.break_coalescing();
+                            poem.cursor_start_line();
                         }
-                        Key::End => poem.cursor_end_line(),
-                        Key::Home => poem.cursor_start_line(),
                         _ => {}
                     }
                     draw_screen(&mut stdout, &poem);
@@ -355,10 +616,6 @@ fn main() {
         }
     }
 
-    write!(
-        stdout,
-        "{}{}",
-        termion::clear::All,
-        termion::cursor::Goto(1, 1)
-    );
+    // No manual cleanup here: dropping `stdout` leaves the alternate screen
+    // and restores the terminal's original mode automatically.
 }
@@ -0,0 +1,356 @@
+//! A piece table for the poem buffer.
+//!
+//! Instead of mutating one big `String` (which makes every insert/delete an
+//! O(n) shift), the document is represented as a sequence of `Piece`s that
+//! each point into one of two backing buffers: the `original` text the file
+//! had when it was loaded, and an append-only `add` buffer holding everything
+//! typed since. Edits only ever touch the `pieces` list and append to `add`,
+//! so they stay cheap no matter how large the document is.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    Original,
+    Add,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Piece {
+    source: Source,
+    start: usize,
+    len: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct PieceTable {
+    original: String,
+    add: String,
+    pieces: Vec<Piece>,
+}
+
+impl PieceTable {
+    pub fn new(original: String) -> Self {
+        let len = original.len();
+        let pieces = if len == 0 {
+            Vec::new()
+        } else {
+            vec![Piece {
+                source: Source::Original,
+                start: 0,
+                len,
+            }]
+        };
+
+        PieceTable {
+            original,
+            add: String::new(),
+            pieces,
+        }
+    }
+
+    /// Total length in bytes, without materialising the text.
+    pub fn len(&self) -> usize {
+        self.pieces.iter().map(|p| p.len).sum()
+    }
+
+    /// Concatenates every piece into the visible text. Used wherever the
+    /// rest of the editor (make_input, draw_screen, saving, ...) needs the
+    /// buffer as a plain string.
+    pub fn text(&self) -> String {
+        let mut out = String::with_capacity(self.len());
+        for piece in &self.pieces {
+            out.push_str(self.slice(piece));
+        }
+        out
+    }
+
+    /// Returns the (column, line) of logical byte offset `pos`, walking
+    /// pieces directly rather than materialising the document into a
+    /// string first. Cheap enough to call on every cursor movement.
+    pub fn cursor_offset(&self, pos: usize) -> (usize, usize) {
+        let mut seen = 0usize;
+        let mut col = 0usize;
+        let mut line = 0usize;
+
+        for piece in &self.pieces {
+            let s = self.slice(piece);
+            if seen + piece.len <= pos {
+                for b in s.bytes() {
+                    if b == b'\n' {
+                        line += 1;
+                        col = 0;
+                    } else {
+                        col += 1;
+                    }
+                }
+                seen += piece.len;
+                continue;
+            }
+
+            let local_target = pos - seen;
+            for (i, b) in s.bytes().enumerate() {
+                if i == local_target {
+                    break;
+                }
+                if b == b'\n' {
+                    line += 1;
+                    col = 0;
+                } else {
+                    col += 1;
+                }
+            }
+            break;
+        }
+
+        (col, line)
+    }
+
+    /// Byte length of line `line_idx` (not counting its newline), without
+    /// materialising the document.
+    pub fn line_len(&self, line_idx: usize) -> usize {
+        let mut line = 0usize;
+        let mut len = 0usize;
+
+        for piece in &self.pieces {
+            for b in self.slice(piece).bytes() {
+                if b == b'\n' {
+                    if line == line_idx {
+                        return len;
+                    }
+                    line += 1;
+                    len = 0;
+                } else if line == line_idx {
+                    len += 1;
+                }
+            }
+        }
+
+        len
+    }
+
+    /// Number of lines, matching `str::lines().count()` semantics (a
+    /// trailing newline doesn't count as an extra empty line), without
+    /// materialising the document.
+    pub fn line_count(&self) -> usize {
+        let mut newlines = 0usize;
+        let mut ends_with_newline = false;
+
+        for piece in &self.pieces {
+            let s = self.slice(piece);
+            newlines += s.bytes().filter(|&b| b == b'\n').count();
+            ends_with_newline = s.as_bytes().last() == Some(&b'\n');
+        }
+
+        if self.len() == 0 {
+            0
+        } else if ends_with_newline {
+            newlines
+        } else {
+            newlines + 1
+        }
+    }
+
+    fn source_str(&self, source: Source) -> &str {
+        match source {
+            Source::Original => &self.original,
+            Source::Add => &self.add,
+        }
+    }
+
+    fn slice(&self, piece: &Piece) -> &str {
+        &self.source_str(piece.source)[piece.start..piece.start + piece.len]
+    }
+
+    /// Appends more text to the original buffer and attaches it as a new
+    /// trailing piece. Used by the lazy file loader to grow the document as
+    /// more of the file is read in, without disturbing existing pieces.
+    pub fn extend_original(&mut self, more: &str) {
+        if more.is_empty() {
+            return;
+        }
+        let start = self.original.len();
+        self.original.push_str(more);
+        self.pieces.push(Piece {
+            source: Source::Original,
+            start,
+            len: more.len(),
+        });
+    }
+
+    /// Finds the piece spanning logical byte offset `pos`, splitting it at
+    /// `pos` if necessary, and returns the index at which a new piece should
+    /// be inserted to land exactly at `pos`.
+    fn split_at(&mut self, pos: usize) -> usize {
+        let mut acc = 0;
+        for idx in 0..self.pieces.len() {
+            let piece = self.pieces[idx];
+            if pos < acc + piece.len {
+                let offset = pos - acc;
+                if offset == 0 {
+                    return idx;
+                }
+                let left = Piece {
+                    source: piece.source,
+                    start: piece.start,
+                    len: offset,
+                };
+                let right = Piece {
+                    source: piece.source,
+                    start: piece.start + offset,
+                    len: piece.len - offset,
+                };
+                self.pieces.splice(idx..idx + 1, [left, right]);
+                return idx + 1;
+            }
+            acc += piece.len;
+        }
+        self.pieces.len()
+    }
+
+    /// Inserts `s` at logical byte offset `pos`.
+    pub fn insert_str(&mut self, pos: usize, s: &str) {
+        if s.is_empty() {
+            return;
+        }
+        let add_start = self.add.len();
+        self.add.push_str(s);
+        let new_piece = Piece {
+            source: Source::Add,
+            start: add_start,
+            len: s.len(),
+        };
+
+        let idx = self.split_at(pos);
+        self.pieces.insert(idx, new_piece);
+    }
+
+    pub fn insert_char(&mut self, pos: usize, c: char) {
+        let mut buf = [0u8; 4];
+        self.insert_str(pos, c.encode_utf8(&mut buf));
+    }
+
+    fn locate_containing(&self, pos: usize) -> (usize, usize) {
+        let mut acc = 0;
+        for (idx, piece) in self.pieces.iter().enumerate() {
+            if pos < acc + piece.len {
+                return (idx, pos - acc);
+            }
+            acc += piece.len;
+        }
+        (self.pieces.len(), 0)
+    }
+
+    /// Removes and returns the single char at logical byte offset `pos`.
+    pub fn delete_char(&mut self, pos: usize) -> char {
+        let (idx, offset) = self.locate_containing(pos);
+        let piece = self.pieces[idx];
+        let c = self.slice(&piece)[offset..].chars().next().unwrap();
+        let clen = c.len_utf8();
+
+        if offset == 0 && clen == piece.len {
+            self.pieces.remove(idx);
+        } else if offset == 0 {
+            self.pieces[idx] = Piece {
+                source: piece.source,
+                start: piece.start + clen,
+                len: piece.len - clen,
+            };
+        } else if offset + clen == piece.len {
+            self.pieces[idx] = Piece {
+                source: piece.source,
+                start: piece.start,
+                len: offset,
+            };
+        } else {
+            let left = Piece {
+                source: piece.source,
+                start: piece.start,
+                len: offset,
+            };
+            let right = Piece {
+                source: piece.source,
+                start: piece.start + offset + clen,
+                len: piece.len - offset - clen,
+            };
+            self.pieces.splice(idx..idx + 1, [left, right]);
+        }
+
+        c
+    }
+
+    /// Removes `n` consecutive chars starting at logical byte offset `pos`.
+    pub fn delete_n(&mut self, pos: usize, n: usize) {
+        for _ in 0..n {
+            self.delete_char(pos);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_str_splits_the_piece_it_lands_in() {
+        let mut pt = PieceTable::new("hello world".to_string());
+        pt.insert_str(5, ",");
+        assert_eq!(pt.text(), "hello, world");
+
+        // Insert again right at the boundary between the inserted comma and
+        // the right half created above, and once more in the middle of the
+        // newly-added piece.
+        pt.insert_str(6, " there");
+        assert_eq!(pt.text(), "hello, there world");
+        pt.insert_str(9, "XX");
+        assert_eq!(pt.text(), "hello, thXXere world");
+    }
+
+    #[test]
+    fn insert_str_at_start_and_end() {
+        let mut pt = PieceTable::new("middle".to_string());
+        pt.insert_str(0, "start-");
+        pt.insert_str(pt.len(), "-end");
+        assert_eq!(pt.text(), "start-middle-end");
+    }
+
+    #[test]
+    fn delete_char_across_piece_boundaries() {
+        let mut pt = PieceTable::new("hello world".to_string());
+        pt.insert_str(5, ",");
+        // Buffer is now "hello, world" split across original+add pieces;
+        // delete the comma we just inserted, which is a whole piece on its
+        // own, then delete a char from each side of the former boundary.
+        assert_eq!(pt.delete_char(5), ',');
+        assert_eq!(pt.text(), "hello world");
+        assert_eq!(pt.delete_char(4), 'o');
+        assert_eq!(pt.text(), "hell world");
+        assert_eq!(pt.delete_char(4), ' ');
+        assert_eq!(pt.text(), "hellworld");
+    }
+
+    #[test]
+    fn delete_n_removes_consecutive_chars_across_pieces() {
+        let mut pt = PieceTable::new("hello world".to_string());
+        pt.insert_str(5, ", dear");
+        assert_eq!(pt.text(), "hello, dear world");
+        pt.delete_n(5, 6);
+        assert_eq!(pt.text(), "hello world");
+    }
+
+    #[test]
+    fn cheap_accessors_match_the_materialised_text() {
+        let mut pt = PieceTable::new("one\ntwo\nthree".to_string());
+        pt.insert_str(7, "TWO-and-a-half\n");
+
+        let text = pt.text();
+        assert_eq!(pt.line_count(), text.lines().count());
+        for (idx, line) in text.lines().enumerate() {
+            assert_eq!(pt.line_len(idx), line.len());
+        }
+
+        for pos in 0..=pt.len() {
+            let expected_line = text[..pos].matches('\n').count();
+            let expected_col = pos - text[..pos].rfind('\n').map(|i| i + 1).unwrap_or(0);
+            assert_eq!(pt.cursor_offset(pos), (expected_col, expected_line));
+        }
+    }
+}
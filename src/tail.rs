@@ -0,0 +1,65 @@
+//! Reading the last few lines of a file without loading the whole thing,
+//! by walking backwards from the end in fixed-size chunks.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+
+const CHUNK_SIZE: usize = 4096;
+
+/// Returns the text of the last `n` lines of the file at `path`, along with
+/// a cursor position pointing at the very end of that text, ready to be fed
+/// into `PieceTable::new`/`draw_screen`.
+pub fn read_last_lines(path: &str, n: usize) -> io::Result<(String, usize)> {
+    if n == 0 {
+        return Ok((String::new(), 0));
+    }
+
+    let mut file = File::open(path)?;
+    let file_len = file.seek(SeekFrom::End(0))?;
+
+    let mut chunks: VecDeque<Vec<u8>> = VecDeque::new();
+    let mut newlines_seen = 0usize;
+    let mut pos = file_len;
+
+    while pos > 0 && newlines_seen <= n {
+        let read_len = CHUNK_SIZE.min(pos as usize);
+        pos -= read_len as u64;
+        file.seek(SeekFrom::Start(pos))?;
+        let mut buf = vec![0u8; read_len];
+        file.read_exact(&mut buf)?;
+        newlines_seen += buf.iter().filter(|&&b| b == b'\n').count();
+        chunks.push_front(buf);
+    }
+
+    let mut bytes = Vec::with_capacity((file_len - pos) as usize);
+    for chunk in chunks {
+        bytes.extend(chunk);
+    }
+
+    // Walk back from the end looking for the newline marking the start of
+    // the n-th-from-last line; everything before it belongs to lines we
+    // don't want. If the last line isn't newline-terminated it still
+    // counts as one of our n lines but doesn't itself end on a newline, so
+    // we only need to find n newlines in that case rather than n+1. If the
+    // file turns out to have fewer than n lines in total, we never find
+    // the target and keep everything we read.
+    let ends_with_newline = bytes.last() == Some(&b'\n');
+    let target = if ends_with_newline { n + 1 } else { n };
+
+    let mut newline_count = 0;
+    let mut cut = 0;
+    for (i, &b) in bytes.iter().enumerate().rev() {
+        if b == b'\n' {
+            newline_count += 1;
+            if newline_count == target {
+                cut = i + 1;
+                break;
+            }
+        }
+    }
+
+    let text = String::from_utf8_lossy(&bytes[cut..]).into_owned();
+    let cursor = text.len();
+    Ok((text, cursor))
+}